@@ -1,12 +1,33 @@
 use std::io;
-use std::io::Write;
-use std::path::Path;
+use std::io::{Read, Write};
+use std::process::{ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use std::path::{Path, PathBuf};
+
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
 
 use argmax::Command;
 
 use crate::error::print_error;
 use crate::exit_codes::ExitCode;
 
+/// Optional wall-clock timeout and (Linux-only) resource limits applied to
+/// a single spawned `--exec` command.
+#[derive(Clone, Copy, Default)]
+pub struct CommandLimits {
+    /// Kill the command if it hasn't finished within this long.
+    pub timeout: Option<Duration>,
+    /// Linux-only: `RLIMIT_CPU`, in seconds of CPU time.
+    pub cpu_seconds: Option<u64>,
+    /// Linux-only: `RLIMIT_AS`, in bytes of virtual address space.
+    pub memory_bytes: Option<u64>,
+}
+
 struct Outputs {
     header: Option<String>,  // 新增：存储 header
     stdout: Vec<u8>,
@@ -29,6 +50,12 @@ impl OutputBuffer {
         self.outputs.push(Outputs { header, stdout, stderr });
     }
 
+    /// Whether entries are NUL- rather than newline-separated, reused as the
+    /// separator convention when piping a matched path into a command's stdin.
+    fn null_separator(&self) -> bool {
+        self.null_separator
+    }
+
     fn write(self) {
         // Avoid taking the lock if there is nothing to do.
         // If null_separator is true, then we still need to write the
@@ -69,46 +96,459 @@ pub fn format_exec_header(cmd: &Command, path: &Path) -> String {
     format!("\n==={} {}===", cmd_name, path_str)
 }
 
+/// Same as `format_exec_header`, but names every stage of a pipeline
+/// (`stage-a | stage-b | stage-c`) rather than a single command.
+pub fn format_pipeline_header(cmds: &[Command], path: &Path) -> String {
+    let pipeline = cmds
+        .iter()
+        .map(|cmd| cmd.get_program().to_string_lossy().into_owned())
+        .collect::<Vec<_>>()
+        .join(" | ");
+    let path_str = path.to_string_lossy();
+    format!("\n==={} {}===", pipeline, path_str)
+}
+
+/// Accumulates bytes read from a child's pipe and yields complete lines
+/// (including the trailing `\n`) to a callback, keeping any trailing
+/// partial line buffered until the next `feed`.
+struct LineBuffer {
+    buf: Vec<u8>,
+}
+
+impl LineBuffer {
+    fn new() -> Self {
+        Self { buf: Vec::new() }
+    }
+
+    fn feed(&mut self, data: &[u8], mut emit: impl FnMut(&[u8])) {
+        self.buf.extend_from_slice(data);
+        while let Some(pos) = self.buf.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buf.drain(..=pos).collect();
+            emit(&line);
+        }
+    }
+
+    /// Flushes whatever is left over once the pipe has hit EOF, even if it
+    /// wasn't newline-terminated.
+    fn flush_remainder(&mut self, mut emit: impl FnMut(&[u8])) {
+        if !self.buf.is_empty() {
+            emit(&self.buf);
+            self.buf.clear();
+        }
+    }
+}
+
+#[cfg(unix)]
+fn set_nonblocking<T: AsRawFd>(stream: &T) -> io::Result<()> {
+    let fd = stream.as_raw_fd();
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// The stdout/stderr locks a streaming command holds from its first line of
+/// output until its pipes are drained.
+type HeldStdio = Option<(io::StdoutLock<'static>, io::StderrLock<'static>)>;
+
+/// Writes one forwarded line to stdout/stderr. On the command's first line the
+/// stdout/stderr locks are taken and `header` is printed; the locks then stay
+/// in `held` until the caller drops them, so other worker threads block on
+/// their next write and this command's lines stay contiguous under its header.
+fn write_forwarded_line(held: &mut HeldStdio, header: Option<&str>, to_stdout: bool, line: &[u8]) {
+    let (out, err) = held.get_or_insert_with(|| {
+        let mut out = io::stdout().lock();
+        if let Some(h) = header {
+            let _ = writeln!(out, "{}", h);
+        }
+        (out, io::stderr().lock())
+    });
+
+    if to_stdout {
+        let _ = out.write_all(line);
+    } else {
+        let _ = err.write_all(line);
+    }
+}
+
+/// Runs `cmd` to completion, forwarding its stdout/stderr line-by-line as the
+/// data arrives instead of buffering the whole output in memory first. All
+/// lines belonging to `cmd` are preceded by `header` (written once, on the
+/// first line of output), keeping per-command grouping intact even though
+/// other threads may be forwarding their own commands' output concurrently:
+/// they wait on their next write until this command's pipes are drained.
+///
+/// On Unix, both pipes are put into non-blocking mode and polled in a single
+/// loop: a `WouldBlock` on one stream just means we move on to the other one,
+/// so a single worker thread can drain both without deadlocking on a full
+/// pipe buffer.
+fn stream_command_output(
+    cmd: &mut Command,
+    header: Option<&str>,
+    limits: &CommandLimits,
+    stdin_path: Option<(&Path, bool)>,
+) -> io::Result<(ExitStatus, bool)> {
+    cmd.stdout(Stdio::piped());
+    cmd.stderr(Stdio::piped());
+    if stdin_path.is_some() {
+        cmd.stdin(Stdio::piped());
+    }
+    prepare_child_limits(cmd, limits, 0);
+
+    let mut child = cmd.spawn()?;
+    if let Some((path, null_separator)) = stdin_path {
+        pipe_path_to_stdin(&mut child, path, null_separator);
+    }
+    let pgid = child.id() as i32;
+    let deadline = limits.timeout.map(|t| Instant::now() + t);
+    let mut child_stdout = child.stdout.take().expect("stdout was piped");
+    let mut child_stderr = child.stderr.take().expect("stderr was piped");
+
+    #[cfg(unix)]
+    {
+        set_nonblocking(&child_stdout)?;
+        set_nonblocking(&child_stderr)?;
+    }
+
+    let mut held: HeldStdio = None;
+    let mut out_buf = LineBuffer::new();
+    let mut err_buf = LineBuffer::new();
+    let mut read_buf = [0u8; 8192];
+    let mut stdout_done = false;
+    let mut stderr_done = false;
+
+    while !stdout_done || !stderr_done {
+        let mut made_progress = false;
+
+        if !stdout_done {
+            match child_stdout.read(&mut read_buf) {
+                Ok(0) => stdout_done = true,
+                Ok(n) => {
+                    made_progress = true;
+                    out_buf.feed(&read_buf[..n], |line| {
+                        write_forwarded_line(&mut held, header, true, line)
+                    });
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(e);
+                }
+            }
+        }
+
+        if !stderr_done {
+            match child_stderr.read(&mut read_buf) {
+                Ok(0) => stderr_done = true,
+                Ok(n) => {
+                    made_progress = true;
+                    err_buf.feed(&read_buf[..n], |line| {
+                        write_forwarded_line(&mut held, header, false, line)
+                    });
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(e);
+                }
+            }
+        }
+
+        // Stop draining once the deadline has passed instead of waiting for
+        // both pipes to hit EOF first — a child that never closes its
+        // stdout/stderr (e.g. a hung or infinite-looping process) would
+        // otherwise spin here forever and the timeout would never fire.
+        if let Some(deadline) = deadline {
+            if Instant::now() >= deadline {
+                break;
+            }
+        }
+
+        if !made_progress {
+            // Nothing was ready on either pipe; avoid busy-spinning while we
+            // wait for the child to produce more output.
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+    }
+
+    out_buf.flush_remainder(|line| write_forwarded_line(&mut held, header, true, line));
+    err_buf.flush_remainder(|line| write_forwarded_line(&mut held, header, false, line));
+
+    // Make sure the header is printed even for a command that produced no
+    // output at all, so the invariant "one header per command" always holds.
+    if held.is_none() {
+        if let Some(h) = header {
+            let stdout = io::stdout();
+            let mut out = stdout.lock();
+            let _ = writeln!(out, "{}", h);
+        }
+    }
+    drop(held);
+
+    // Reuse the same deadline the read loop just watched, rather than
+    // starting a fresh one here — otherwise a command that keeps running
+    // after draining its pipes would get up to `limits.timeout` twice.
+    wait_with_deadline(&mut child, deadline, pgid)
+}
+
+/// Sets up process-group membership and, on Linux, the `RLIMIT_CPU`/`RLIMIT_AS`
+/// limits requested in `limits`, so that a runaway command is bounded by the
+/// kernel rather than relying solely on us noticing and killing it.
+///
+/// `pgid` is the process group the child should join: `0` creates a new
+/// group equal to the child's own pid (the common case, one command per
+/// group), while a pipeline passes the first stage's pid so every stage ends
+/// up in the *same* group and a single `killpg` can terminate all of them.
+fn prepare_child_limits(cmd: &mut Command, limits: &CommandLimits, pgid: i32) {
+    #[cfg(unix)]
+    cmd.process_group(pgid);
+    #[cfg(not(unix))]
+    let _ = pgid;
+
+    #[cfg(target_os = "linux")]
+    {
+        let cpu_seconds = limits.cpu_seconds;
+        let memory_bytes = limits.memory_bytes;
+        if cpu_seconds.is_some() || memory_bytes.is_some() {
+            unsafe {
+                cmd.pre_exec(move || {
+                    if let Some(secs) = cpu_seconds {
+                        let rlim = libc::rlimit {
+                            rlim_cur: secs,
+                            rlim_max: secs,
+                        };
+                        if libc::setrlimit(libc::RLIMIT_CPU, &rlim) != 0 {
+                            return Err(io::Error::last_os_error());
+                        }
+                    }
+                    if let Some(bytes) = memory_bytes {
+                        let rlim = libc::rlimit {
+                            rlim_cur: bytes,
+                            rlim_max: bytes,
+                        };
+                        if libc::setrlimit(libc::RLIMIT_AS, &rlim) != 0 {
+                            return Err(io::Error::last_os_error());
+                        }
+                    }
+                    Ok(())
+                });
+            }
+        }
+    }
+}
+
+/// Writes the matched path into the child's stdin, terminated by `\n` or,
+/// if `null_separator` is set, `\0` (the same convention `OutputBuffer` uses
+/// for its own separators). Dropping the stdin handle afterwards closes the
+/// write end, signalling EOF to the child.
+fn pipe_path_to_stdin(child: &mut std::process::Child, path: &Path, null_separator: bool) {
+    if let Some(mut stdin) = child.stdin.take() {
+        let separator: &[u8] = if null_separator { b"\0" } else { b"\n" };
+        let _ = stdin.write_all(path.to_string_lossy().as_bytes());
+        let _ = stdin.write_all(separator);
+        let _ = stdin.flush();
+    }
+    // `stdin` is dropped here (if it was taken), closing the pipe.
+}
+
+#[cfg(unix)]
+fn kill_process_group(pgid: i32, signal: libc::c_int) {
+    unsafe {
+        libc::killpg(pgid as libc::pid_t, signal);
+    }
+}
+
+/// Waits for `child` to exit, killing its process group (`pgid`, as set up by
+/// `prepare_child_limits`) if `deadline` passes first.
+///
+/// On Unix this is a `waitpid`-style poll loop (`try_wait` on a short sleep)
+/// rather than a blocking wait, so that we notice the deadline. On timeout,
+/// `SIGTERM` is sent to `pgid` first; if the child hasn't exited after a
+/// short grace period, `SIGKILL` follows. Killing by process group (rather
+/// than `child.kill()`, which only targets this one pid) lets callers put
+/// several children in the same group — e.g. every stage of a pipeline — so
+/// one deadline terminates all of them together.
+fn wait_with_deadline(
+    child: &mut std::process::Child,
+    deadline: Option<Instant>,
+    #[cfg_attr(not(unix), allow(unused_variables))] pgid: i32,
+) -> io::Result<(ExitStatus, bool)> {
+    let Some(deadline) = deadline else {
+        return Ok((child.wait()?, false));
+    };
+
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok((status, false));
+        }
+        if Instant::now() >= deadline {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    #[cfg(unix)]
+    kill_process_group(pgid, libc::SIGTERM);
+    #[cfg(not(unix))]
+    let _ = child.kill();
+
+    let grace_deadline = Instant::now() + Duration::from_millis(200);
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok((status, true));
+        }
+        if Instant::now() >= grace_deadline {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    #[cfg(unix)]
+    kill_process_group(pgid, libc::SIGKILL);
+    #[cfg(not(unix))]
+    let _ = child.kill();
+
+    Ok((child.wait()?, true))
+}
+
+/// Waits for a single command's child to exit within `timeout` (its own
+/// process group, created by `prepare_child_limits(.., 0)`, is what gets
+/// killed on expiry).
+fn wait_with_timeout(
+    child: &mut std::process::Child,
+    timeout: Option<Duration>,
+) -> io::Result<(ExitStatus, bool)> {
+    let deadline = timeout.map(|t| Instant::now() + t);
+    let pgid = child.id() as i32;
+    wait_with_deadline(child, deadline, pgid)
+}
+
+/// Runs `cmd` to completion under the given `limits`, capturing its stdout
+/// and stderr the way `Command::output` would. Unlike `Command::output`,
+/// this can terminate the child early if `limits.timeout` elapses, in which
+/// case the returned `timed_out` flag is set.
+fn run_with_limits(
+    cmd: &mut Command,
+    limits: &CommandLimits,
+    stdin_path: Option<(&Path, bool)>,
+) -> io::Result<(ExitStatus, bool, Vec<u8>, Vec<u8>)> {
+    cmd.stdout(Stdio::piped());
+    cmd.stderr(Stdio::piped());
+    if stdin_path.is_some() {
+        cmd.stdin(Stdio::piped());
+    }
+    prepare_child_limits(cmd, limits, 0);
+
+    let mut child = cmd.spawn()?;
+    if let Some((path, null_separator)) = stdin_path {
+        pipe_path_to_stdin(&mut child, path, null_separator);
+    }
+    let mut child_stdout = child.stdout.take().expect("stdout was piped");
+    let mut child_stderr = child.stderr.take().expect("stderr was piped");
+
+    // Drain both pipes on their own threads so a full pipe buffer can't
+    // deadlock the timeout loop below.
+    let stdout_thread = thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = child_stdout.read_to_end(&mut buf);
+        buf
+    });
+    let stderr_thread = thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = child_stderr.read_to_end(&mut buf);
+        buf
+    });
+
+    let (status, timed_out) = wait_with_timeout(&mut child, limits.timeout)?;
+    let stdout = stdout_thread.join().unwrap_or_default();
+    let stderr = stderr_thread.join().unwrap_or_default();
+
+    Ok((status, timed_out, stdout, stderr))
+}
+
 /// Executes a command.
 pub fn execute_commands<I: Iterator<Item = io::Result<Command>>>(
     cmds: I,
     mut output_buffer: OutputBuffer,
     enable_output_buffering: bool,
     header: Option<String>,  // 新增参数
+    stream_output: bool,  // 新增：逐行流式转发，避免整条输出都缓冲在内存中
+    limits: CommandLimits,  // 新增：超时和（Linux 上的）资源限制
+    stdin_path: Option<PathBuf>,  // 新增：把匹配到的路径写入第一条命令的 stdin，而不是作为参数
+    keep_going: bool,  // 新增：即使某条命令失败，也继续执行剩余命令，最后汇总退出码
 ) -> ExitCode {
+    let null_separator = output_buffer.null_separator();
     let mut first = true;
-    
+    // In keep-going mode this records the most recent non-zero outcome so
+    // it can be reported once every command has had a chance to run; in
+    // fail-fast mode (the default) we always return before it's ever set.
+    let mut last_failure: Option<ExitCode> = None;
+
+    // Records a failed outcome: in keep-going mode it's stashed in
+    // `last_failure` and execution moves on, otherwise we write the
+    // buffered output and bail out immediately, exactly like before.
+    macro_rules! on_failure {
+        ($code:expr) => {{
+            let code = $code;
+            if keep_going {
+                last_failure = Some(code);
+            } else {
+                output_buffer.write();
+                return code;
+            }
+        }};
+    }
+
     for result in cmds {
         let mut cmd = match result {
             Ok(cmd) => cmd,
-            Err(e) => return handle_cmd_error(None, e),
+            Err(e) => {
+                on_failure!(handle_cmd_error(None, e));
+                continue;
+            }
         };
 
-        // Spawn the supplied command.
-        let output = if enable_output_buffering {
-            cmd.output()
+        // Only the very first command (as opposed to a later command of the
+        // same set) receives the path on stdin.
+        let cmd_stdin_path = if first {
+            stdin_path.as_deref().map(|p| (p, null_separator))
         } else {
-            // If running on only one thread, don't buffer output
-            // Allows for viewing and interacting with intermediate command output
-            
-            // 非缓冲模式下，直接打印 header
-            if first {
-                if let Some(ref h) = header {
-                    let stdout = io::stdout();
-                    let mut handle = stdout.lock();
-                    let _ = writeln!(handle, "{}", h);
-                    let _ = handle.flush();
-                }
+            None
+        };
+
+        // Streaming mode forwards stdout/stderr line-by-line as the child
+        // produces it, so it bypasses the `Outputs` buffering below
+        // entirely; peak memory is bounded by a line, not the whole output.
+        if enable_output_buffering && stream_output {
+            let cmd_header = if first {
                 first = false;
+                header.as_deref()
+            } else {
+                None
+            };
+
+            match stream_command_output(&mut cmd, cmd_header, &limits, cmd_stdin_path) {
+                Ok((status, timed_out)) => {
+                    if timed_out {
+                        on_failure!(ExitCode::Timeout);
+                    } else if status.code() != Some(0) {
+                        on_failure!(ExitCode::GeneralError);
+                    }
+                }
+                Err(why) => on_failure!(handle_cmd_error(Some(&cmd), why)),
             }
-            
-            cmd.spawn().and_then(|c| c.wait_with_output())
-        };
+            continue;
+        }
 
-        // Then wait for the command to exit, if it was spawned.
-        match output {
-            Ok(output) => {
-                if enable_output_buffering {
+        if enable_output_buffering {
+            match run_with_limits(&mut cmd, &limits, cmd_stdin_path) {
+                Ok((status, timed_out, stdout, stderr)) => {
                     // 缓冲模式下，将 header 与输出一起存储
                     let h = if first {
                         first = false;
@@ -116,21 +556,61 @@ pub fn execute_commands<I: Iterator<Item = io::Result<Command>>>(
                     } else {
                         None
                     };
-                    output_buffer.push(h, output.stdout, output.stderr);
+                    output_buffer.push(h, stdout, stderr);
+
+                    if timed_out {
+                        on_failure!(ExitCode::Timeout);
+                    } else if status.code() != Some(0) {
+                        on_failure!(ExitCode::GeneralError);
+                    }
                 }
-                if output.status.code() != Some(0) {
-                    output_buffer.write();
-                    return ExitCode::GeneralError;
+                Err(why) => {
+                    first = false;
+                    on_failure!(handle_cmd_error(Some(&cmd), why))
                 }
             }
-            Err(why) => {
-                output_buffer.write();
-                return handle_cmd_error(Some(&cmd), why);
+            continue;
+        }
+
+        // If running on only one thread, don't buffer output.
+        // Allows for viewing and interacting with intermediate command output.
+
+        // 非缓冲模式下，直接打印 header
+        if first {
+            if let Some(ref h) = header {
+                let stdout = io::stdout();
+                let mut handle = stdout.lock();
+                let _ = writeln!(handle, "{}", h);
+                let _ = handle.flush();
             }
+            first = false;
+        }
+
+        if cmd_stdin_path.is_some() {
+            cmd.stdin(Stdio::piped());
+        }
+        prepare_child_limits(&mut cmd, &limits, 0);
+        match cmd.spawn() {
+            Ok(mut child) => {
+                if let Some((path, null_sep)) = cmd_stdin_path {
+                    pipe_path_to_stdin(&mut child, path, null_sep);
+                }
+                match wait_with_timeout(&mut child, limits.timeout) {
+                    Ok((status, timed_out)) => {
+                        if timed_out {
+                            on_failure!(ExitCode::Timeout);
+                        } else if status.code() != Some(0) {
+                            on_failure!(ExitCode::GeneralError);
+                        }
+                    }
+                    Err(why) => on_failure!(handle_cmd_error(Some(&cmd), why)),
+                }
+            }
+            Err(why) => on_failure!(handle_cmd_error(Some(&cmd), why)),
         }
     }
     output_buffer.write();
-    ExitCode::Success
+    last_failure.unwrap_or(ExitCode::Success)
 }
 
 pub fn handle_cmd_error(cmd: Option<&Command>, err: io::Error) -> ExitCode {
@@ -148,3 +628,558 @@ pub fn handle_cmd_error(cmd: Option<&Command>, err: io::Error) -> ExitCode {
         }
     }
 }
+
+/// Sends `SIGKILL` to `pgid` (if any stage ever spawned) and reaps every
+/// already-spawned child, so a spawn or wait failure partway through a
+/// pipeline doesn't leave earlier stages running (or zombied) behind.
+fn kill_and_reap_all(children: &mut [std::process::Child], #[cfg(unix)] pgid: Option<i32>) {
+    #[cfg(unix)]
+    if let Some(pgid) = pgid {
+        kill_process_group(pgid, libc::SIGKILL);
+    }
+    #[cfg(not(unix))]
+    for child in children.iter_mut() {
+        let _ = child.kill();
+    }
+    for child in children.iter_mut() {
+        let _ = child.wait();
+    }
+}
+
+/// Runs an ordered pipeline of commands for a single matched path, duct-style:
+/// stage N's stdout is wired directly into stage N+1's stdin. Only the final
+/// stage's stdout ends up in `output_buffer`; every stage's stderr is merged
+/// together, the same way a shell pipeline surfaces errors from any stage. A
+/// non-zero exit from *any* stage fails the whole pipeline, and `header`
+/// (typically built with `format_pipeline_header`) should already name every
+/// stage.
+///
+/// Every stage joins the *same* process group (the first stage's pid) via
+/// `prepare_child_limits`, and `limits.timeout` is a single deadline shared
+/// across all stages rather than restarting for each one — both matching how
+/// `limits` behaves for a single command, just applied to the whole chain.
+pub fn execute_pipeline(
+    mut cmds: Vec<Command>,
+    header: Option<String>,
+    mut output_buffer: OutputBuffer,
+    limits: CommandLimits,
+    stdin_path: Option<PathBuf>,
+) -> ExitCode {
+    if cmds.is_empty() {
+        output_buffer.write();
+        return ExitCode::Success;
+    }
+
+    let null_separator = output_buffer.null_separator();
+    let mut children: Vec<std::process::Child> = Vec::with_capacity(cmds.len());
+    let mut prev_stdout = None;
+    #[cfg(unix)]
+    let mut pgid: Option<i32> = None;
+
+    for (i, cmd) in cmds.iter_mut().enumerate() {
+        if let Some(stdout) = prev_stdout.take() {
+            cmd.stdin(Stdio::from(stdout));
+        } else if i == 0 && stdin_path.is_some() {
+            cmd.stdin(Stdio::piped());
+        }
+        cmd.stdout(Stdio::piped());
+        cmd.stderr(Stdio::piped());
+        #[cfg(unix)]
+        prepare_child_limits(cmd, &limits, pgid.unwrap_or(0));
+        #[cfg(not(unix))]
+        prepare_child_limits(cmd, &limits, 0);
+
+        let mut child = match cmd.spawn() {
+            Ok(child) => child,
+            Err(why) => {
+                #[cfg(unix)]
+                kill_and_reap_all(&mut children, pgid);
+                #[cfg(not(unix))]
+                kill_and_reap_all(&mut children);
+                output_buffer.write();
+                return handle_cmd_error(Some(cmd), why);
+            }
+        };
+
+        #[cfg(unix)]
+        pgid.get_or_insert(child.id() as i32);
+
+        if i == 0 {
+            if let Some(path) = stdin_path.as_deref() {
+                pipe_path_to_stdin(&mut child, path, null_separator);
+            }
+        }
+
+        prev_stdout = child.stdout.take();
+        children.push(child);
+    }
+
+    // Merge every stage's stderr concurrently, so that a stage blocked on a
+    // full stderr pipe can't wedge the others.
+    let stderr_threads: Vec<_> = children
+        .iter_mut()
+        .map(|child| {
+            let mut stderr = child.stderr.take().expect("stderr was piped");
+            thread::spawn(move || {
+                let mut buf = Vec::new();
+                let _ = stderr.read_to_end(&mut buf);
+                buf
+            })
+        })
+        .collect();
+
+    // Only the last stage's stdout is part of the pipeline's output.
+    let mut final_stdout = children
+        .last_mut()
+        .and_then(|child| child.stdout.take())
+        .expect("last stage's stdout was piped");
+    let stdout_thread = thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = final_stdout.read_to_end(&mut buf);
+        buf
+    });
+
+    // One deadline for the whole pipeline, not one per stage.
+    let deadline = limits.timeout.map(|t| Instant::now() + t);
+    #[cfg(unix)]
+    let wait_pgid = pgid.unwrap_or(0);
+    #[cfg(not(unix))]
+    let wait_pgid = 0;
+
+    let mut pipeline_failed = false;
+    let mut pipeline_timed_out = false;
+    let mut wait_error = None;
+
+    for (i, child) in children.iter_mut().enumerate() {
+        match wait_with_deadline(child, deadline, wait_pgid) {
+            Ok((status, timed_out)) => {
+                pipeline_timed_out |= timed_out;
+                pipeline_failed |= status.code() != Some(0);
+            }
+            Err(why) => {
+                wait_error = Some((i, why));
+                break;
+            }
+        }
+    }
+
+    if let Some((i, why)) = wait_error {
+        #[cfg(unix)]
+        kill_and_reap_all(&mut children[i..], pgid);
+        #[cfg(not(unix))]
+        kill_and_reap_all(&mut children[i..]);
+        output_buffer.write();
+        return handle_cmd_error(cmds.get(i), why);
+    }
+
+    let stdout = stdout_thread.join().unwrap_or_default();
+    let stderr = stderr_threads
+        .into_iter()
+        .flat_map(|handle| handle.join().unwrap_or_default())
+        .collect();
+
+    output_buffer.push(header, stdout, stderr);
+    output_buffer.write();
+
+    if pipeline_timed_out {
+        ExitCode::Timeout
+    } else if pipeline_failed {
+        ExitCode::GeneralError
+    } else {
+        ExitCode::Success
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A command that sleeps longer than its configured timeout should be
+    /// killed and reported as `ExitCode::Timeout`, whether or not output is
+    /// forwarded line-by-line as it streams in.
+    fn assert_timeout_kills_sleep(stream_output: bool) {
+        let limits = CommandLimits {
+            timeout: Some(Duration::from_millis(100)),
+            cpu_seconds: None,
+            memory_bytes: None,
+        };
+        let cmds = std::iter::once(Ok({
+            let mut cmd = Command::new("sleep");
+            cmd.arg("5");
+            cmd
+        }));
+
+        let started = Instant::now();
+        let code = execute_commands(
+            cmds,
+            OutputBuffer::new(false),
+            true,
+            None,
+            stream_output,
+            limits,
+            None,
+            false,
+        );
+
+        assert_eq!(code, ExitCode::Timeout);
+        // The command would run for 5s if the timeout weren't enforced;
+        // give plenty of slack over the 100ms timeout for CI scheduling
+        // noise while still failing fast if enforcement regresses.
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn buffered_mode_enforces_timeout() {
+        assert_timeout_kills_sleep(false);
+    }
+
+    #[test]
+    fn streaming_mode_enforces_timeout() {
+        assert_timeout_kills_sleep(true);
+    }
+
+    /// Redirects the real stdout/stderr file descriptors for the duration of
+    /// `f`, returning whatever was written to each. Needed because
+    /// `write_forwarded_line`/`OutputBuffer::write` always write to the
+    /// process's actual stdout/stderr rather than something swappable.
+    #[cfg(unix)]
+    fn capture_stdio<F: FnOnce()>(f: F) -> (Vec<u8>, Vec<u8>) {
+        use std::os::unix::io::FromRawFd;
+
+        fn redirect(target_fd: i32) -> (std::fs::File, i32) {
+            let mut fds = [0i32; 2];
+            unsafe {
+                libc::pipe(fds.as_mut_ptr());
+            }
+            let saved = unsafe { libc::dup(target_fd) };
+            unsafe {
+                libc::dup2(fds[1], target_fd);
+                libc::close(fds[1]);
+            }
+            (unsafe { std::fs::File::from_raw_fd(fds[0]) }, saved)
+        }
+
+        let _ = io::stdout().flush();
+        let _ = io::stderr().flush();
+        let (mut stdout_read, stdout_saved) = redirect(1);
+        let (mut stderr_read, stderr_saved) = redirect(2);
+
+        f();
+
+        let _ = io::stdout().flush();
+        let _ = io::stderr().flush();
+        unsafe {
+            libc::dup2(stdout_saved, 1);
+            libc::close(stdout_saved);
+            libc::dup2(stderr_saved, 2);
+            libc::close(stderr_saved);
+        }
+
+        let mut stdout_buf = Vec::new();
+        let mut stderr_buf = Vec::new();
+        let _ = stdout_read.read_to_end(&mut stdout_buf);
+        let _ = stderr_read.read_to_end(&mut stderr_buf);
+        (stdout_buf, stderr_buf)
+    }
+
+    /// The entire point of `write_forwarded_line` keeping the stdio locks from
+    /// a command's first line on is that concurrent worker threads streaming
+    /// different commands never interleave mid-command: every line belonging
+    /// to one command must stay contiguous under that command's own header.
+    #[cfg(unix)]
+    #[test]
+    fn concurrent_streaming_keeps_each_commands_lines_grouped_under_its_header() {
+        const N: usize = 4;
+        let (stdout, _stderr) = capture_stdio(|| {
+            let handles: Vec<_> = (0..N)
+                .map(|i| {
+                    std::thread::spawn(move || {
+                        let mut cmd = Command::new("printf");
+                        cmd.arg(format!("cmd{i}-line-a\ncmd{i}-line-b\ncmd{i}-line-c\n"));
+                        let header = format!("===header-{i}===");
+                        stream_command_output(&mut cmd, Some(&header), &CommandLimits::default(), None)
+                            .expect("stream_command_output");
+                    })
+                })
+                .collect();
+            for handle in handles {
+                handle.join().unwrap();
+            }
+        });
+
+        let text = String::from_utf8(stdout).expect("utf8 stdout");
+
+        for i in 0..N {
+            let header = format!("===header-{i}===");
+            let start = text
+                .find(&header)
+                .unwrap_or_else(|| panic!("missing header for cmd{i}"));
+            let rest = &text[start + header.len()..];
+            let end = rest.find("===header-").unwrap_or(rest.len());
+            let block = &rest[..end];
+            for line in block.lines().filter(|l| !l.is_empty()) {
+                assert!(
+                    line.starts_with(&format!("cmd{i}-")),
+                    "line {line:?} from another command leaked into cmd{i}'s header block"
+                );
+            }
+        }
+    }
+
+    /// The matched path must reach the child on stdin, terminated by the
+    /// configured separator, rather than ever being passed as an argv
+    /// element (the command here is given no arguments at all, so the only
+    /// way the path can show up in its output is via stdin).
+    #[test]
+    fn pipe_path_to_stdin_writes_path_with_configured_separator() {
+        for null_separator in [false, true] {
+            let mut cmd = Command::new("cat");
+            cmd.stdin(Stdio::piped());
+            cmd.stdout(Stdio::piped());
+            let mut child = cmd.spawn().expect("spawn cat");
+
+            let path = Path::new("/tmp/some matched path.txt");
+            pipe_path_to_stdin(&mut child, path, null_separator);
+
+            let mut stdout = child.stdout.take().expect("stdout was piped");
+            let mut actual = Vec::new();
+            stdout.read_to_end(&mut actual).expect("read cat output");
+            let _ = child.wait();
+
+            let mut expected = path.to_string_lossy().into_owned().into_bytes();
+            expected.extend_from_slice(if null_separator { b"\0" } else { b"\n" });
+            assert_eq!(actual, expected);
+        }
+    }
+
+    /// Only the final stage's stdout should end up in `output_buffer`, but
+    /// every stage's stderr is merged in regardless of which stage produced
+    /// it (and a non-final stage's failure still fails the whole pipeline).
+    #[cfg(unix)]
+    #[test]
+    fn pipeline_captures_only_final_stdout_but_every_stages_stderr() {
+        let mut stage0 = Command::new("sh");
+        stage0.arg("-c");
+        stage0.arg("printf 'hello\\n'; printf 'stage0 failed\\n' 1>&2; exit 1");
+        let stage1 = Command::new("cat");
+
+        let (stdout, stderr) = capture_stdio(|| {
+            let code = execute_pipeline(
+                vec![stage0, stage1],
+                None,
+                OutputBuffer::new(false),
+                CommandLimits::default(),
+                None,
+            );
+            assert_eq!(code, ExitCode::GeneralError);
+        });
+
+        assert_eq!(stdout, b"hello\n");
+        assert!(
+            String::from_utf8_lossy(&stderr).contains("stage0 failed"),
+            "expected the failing earlier stage's stderr to still be merged into the pipeline's output"
+        );
+    }
+
+    /// A timeout must kill every stage sharing the pipeline's process group,
+    /// not just whichever stage the wait loop happens to be on — otherwise an
+    /// earlier stage (here, the `sleep`) would be left running.
+    #[cfg(unix)]
+    #[test]
+    fn pipeline_timeout_kills_every_stage_via_shared_process_group() {
+        let marker = std::env::temp_dir().join(format!("fd-pipeline-pgid-test-{}", std::process::id()));
+        let _ = std::fs::remove_file(&marker);
+
+        let mut stage0 = Command::new("sh");
+        stage0.arg("-c");
+        stage0.arg(format!("echo $$ > {} ; exec sleep 5", marker.display()));
+        let stage1 = Command::new("cat");
+
+        let limits = CommandLimits {
+            timeout: Some(Duration::from_millis(200)),
+            cpu_seconds: None,
+            memory_bytes: None,
+        };
+
+        let started = Instant::now();
+        let code = execute_pipeline(
+            vec![stage0, stage1],
+            None,
+            OutputBuffer::new(false),
+            limits,
+            None,
+        );
+        assert_eq!(code, ExitCode::Timeout);
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        // Give the kernel a moment to finish tearing the group down.
+        std::thread::sleep(Duration::from_millis(100));
+        let pid: i32 = std::fs::read_to_string(&marker)
+            .expect("stage0 should have written its pid before the timeout fired")
+            .trim()
+            .parse()
+            .expect("valid pid");
+        let _ = std::fs::remove_file(&marker);
+
+        // signal 0 just probes whether the pid is alive; ESRCH means it's
+        // gone, which is what we want once the pipeline group is killed.
+        let alive = unsafe { libc::kill(pid, 0) } == 0;
+        assert!(!alive, "stage0 (sleep) survived the pipeline's timeout kill");
+    }
+
+    /// Runs a failing command followed by a command that leaves a side
+    /// effect (creating `marker`), returning whether the marker exists
+    /// afterward so callers can tell whether the second command actually ran.
+    fn run_failure_then_marker_touch(keep_going: bool) -> (ExitCode, bool) {
+        let marker = std::env::temp_dir().join(format!(
+            "fd-keep-going-test-{}-{}",
+            std::process::id(),
+            keep_going
+        ));
+        let _ = std::fs::remove_file(&marker);
+
+        let cmds = vec![Command::new("false"), {
+            let mut cmd = Command::new("touch");
+            cmd.arg(&marker);
+            cmd
+        }];
+
+        let code = execute_commands(
+            cmds.into_iter().map(Ok),
+            OutputBuffer::new(false),
+            true,
+            None,
+            false,
+            CommandLimits::default(),
+            None,
+            keep_going,
+        );
+
+        let ran_second = marker.exists();
+        let _ = std::fs::remove_file(&marker);
+        (code, ran_second)
+    }
+
+    #[test]
+    fn keep_going_runs_every_command_after_an_earlier_failure() {
+        let (code, ran_second) = run_failure_then_marker_touch(true);
+        assert_eq!(code, ExitCode::GeneralError);
+        assert!(
+            ran_second,
+            "keep_going=true should still run later commands after an earlier one fails"
+        );
+    }
+
+    #[test]
+    fn fail_fast_stops_before_later_commands() {
+        let (code, ran_second) = run_failure_then_marker_touch(false);
+        assert_eq!(code, ExitCode::GeneralError);
+        assert!(
+            !ran_second,
+            "keep_going=false should stop at the first failure, same as before keep-going existed"
+        );
+    }
+
+    /// Killing just the directly-spawned child on timeout isn't enough if it
+    /// has backgrounded a grandchild into the same process group; the
+    /// grandchild (here, the backgrounded `sleep 5`) must not survive either.
+    #[cfg(unix)]
+    #[test]
+    fn timeout_kills_backgrounded_grandchildren_too() {
+        let marker = std::env::temp_dir().join(format!("fd-grandchild-test-{}", std::process::id()));
+        let _ = std::fs::remove_file(&marker);
+
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c");
+        cmd.arg(format!(
+            "sleep 5 & echo $! > {} ; wait",
+            marker.display()
+        ));
+
+        let limits = CommandLimits {
+            timeout: Some(Duration::from_millis(200)),
+            cpu_seconds: None,
+            memory_bytes: None,
+        };
+
+        let (_, timed_out, _, _) =
+            run_with_limits(&mut cmd, &limits, None).expect("run_with_limits");
+        assert!(timed_out);
+
+        // Give the kernel a moment to finish tearing the group down.
+        std::thread::sleep(Duration::from_millis(100));
+        let pid: i32 = std::fs::read_to_string(&marker)
+            .expect("grandchild pid marker should have been written before the timeout fired")
+            .trim()
+            .parse()
+            .expect("valid pid");
+        let _ = std::fs::remove_file(&marker);
+
+        let alive = unsafe { libc::kill(pid, 0) } == 0;
+        assert!(!alive, "backgrounded grandchild sleep survived the timeout kill");
+    }
+
+    /// `RLIMIT_CPU` should terminate a CPU-bound busy loop on its own, well
+    /// before the (much longer) wall-clock timeout ever gets a chance to.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn cpu_rlimit_terminates_busy_loop() {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c");
+        cmd.arg("while :; do :; done");
+
+        let limits = CommandLimits {
+            timeout: Some(Duration::from_secs(10)),
+            cpu_seconds: Some(1),
+            memory_bytes: None,
+        };
+
+        let started = Instant::now();
+        let (status, timed_out, _, _) =
+            run_with_limits(&mut cmd, &limits, None).expect("run_with_limits");
+
+        assert!(
+            !timed_out,
+            "RLIMIT_CPU should have killed the process before the wall-clock timeout did"
+        );
+        assert!(
+            !status.success(),
+            "a busy loop exceeding its CPU limit should be killed, not exit cleanly"
+        );
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    /// `RLIMIT_AS` should stop a process from growing past the configured
+    /// address-space ceiling, independent of the CPU-time limit above.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn memory_rlimit_terminates_over_allocating_process() {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c");
+        // Grows a shell variable to ~200MB; with RLIMIT_AS capped far below
+        // that, the allocation (and the process) should fail.
+        cmd.arg("v=$(yes | head -c 200000000); echo done");
+
+        let limits = CommandLimits {
+            timeout: Some(Duration::from_secs(10)),
+            cpu_seconds: None,
+            memory_bytes: Some(20 * 1024 * 1024),
+        };
+
+        let (status, timed_out, stdout, _) =
+            run_with_limits(&mut cmd, &limits, None).expect("run_with_limits");
+
+        assert!(
+            !timed_out,
+            "RLIMIT_AS should have killed the process before the wall-clock timeout did"
+        );
+        assert!(
+            !status.success(),
+            "a process over-allocating past its RLIMIT_AS should be killed, not exit cleanly"
+        );
+        assert!(
+            !stdout.ends_with(b"done\n"),
+            "process should have died before reaching the final echo"
+        );
+    }
+}
@@ -0,0 +1,28 @@
+use std::process;
+
+/// Wrapper around `std::process::exit` with a strongly-typed set of reasons
+/// for exiting with something other than success.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitCode {
+    Success,
+    GeneralError,
+    /// A spawned `--exec` command was killed after exceeding its configured
+    /// timeout instead of running to completion.
+    Timeout,
+}
+
+impl ExitCode {
+    pub fn exit(self) -> ! {
+        process::exit(self.into());
+    }
+}
+
+impl From<ExitCode> for i32 {
+    fn from(code: ExitCode) -> i32 {
+        match code {
+            ExitCode::Success => 0,
+            ExitCode::GeneralError => 1,
+            ExitCode::Timeout => 124,
+        }
+    }
+}